@@ -1,16 +1,125 @@
-use bytebuilder::{builder::ByteBuilder, reader::ByteReader, traits::Byteable};
+//! With the `std` feature off this crate is `#![no_std]` and only pulls in
+//! `alloc`. `bytebuilder` itself is a plain `std` crate today and is only
+//! pulled in when `std` is enabled (it's an optional dependency gated by the
+//! feature, see `Cargo.toml`), used solely to provide the `IMF<T>: Byteable`
+//! interop impl. The crate's own (de)serialization - [`IMF::to_bytes_rle`] /
+//! [`IMF::from_bytes_rle`] - never touches `bytebuilder` and works the same
+//! with `std` on or off.
+#![cfg_attr(not(feature = "std"), no_std)]
+
+#[cfg(not(feature = "std"))]
+extern crate alloc;
+
+#[cfg(feature = "std")]
+use std::fmt;
+#[cfg(not(feature = "std"))]
+use core::fmt;
+
+#[cfg(feature = "std")]
+use std::vec::Vec;
+#[cfg(not(feature = "std"))]
+use alloc::vec;
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+
+#[cfg(feature = "std")]
+use bytebuilder::traits::Byteable;
 
 pub type TileType = i16;
 pub type DimensionType = u32;
 
+/// `IMF<i16>`, preserved for callers that don't need a wider or narrower tile scalar.
+pub type ImfI16 = IMF<i16>;
+
+/// The scalars an [`IMF`] can store as a tile value.
+///
+/// This mirrors `bytebuilder::traits::Byteable`'s shape, but is defined locally
+/// rather than bounding `IMF<T>` on `bytebuilder::traits::Byteable` directly:
+/// `bytebuilder` is a foreign crate and doesn't provide blanket impls for
+/// primitives, so the orphan rule blocks `imf` from ever implementing
+/// `Byteable` for `i16`/`u32`/etc. itself. Implementing `TileScalar` for them
+/// here is legal since the trait is local, and it's also what lets `IMF`'s own
+/// (de)serialization work without `bytebuilder` at all (see [`IMF::to_bytes_rle`]).
+pub trait TileScalar: Sized {
+    fn to_bytes(&self) -> Vec<u8>;
+    fn from_bytes(bytes: &[u8]) -> Option<Self>;
+}
+
+macro_rules! impl_tile_scalar {
+    ($($t:ty),*) => {
+        $(
+            impl TileScalar for $t {
+                fn to_bytes(&self) -> Vec<u8> {
+                    self.to_be_bytes().to_vec()
+                }
+                fn from_bytes(bytes: &[u8]) -> Option<Self> {
+                    let arr: [u8; core::mem::size_of::<$t>()] = bytes.try_into().ok()?;
+                    Some(<$t>::from_be_bytes(arr))
+                }
+            }
+        )*
+    };
+}
+impl_tile_scalar!(i8, u8, i16, u16, i32, u32, i64, u64);
+
+/// A minimal, dependency-free byte encoder for [`IMF`]'s own v3/v4 wire format.
+/// Kept separate from `bytebuilder::builder::ByteBuilder` so the core
+/// encode/decode path needs only `alloc`, not `bytebuilder` (which is a plain
+/// `std` crate today).
+struct RawBuilder {
+    bytes: Vec<u8>,
+}
+impl RawBuilder {
+    fn new() -> Self {
+        Self { bytes: Vec::new() }
+    }
+    fn push_u8(&mut self, v: u8) {
+        self.bytes.push(v);
+    }
+    fn push_u32(&mut self, v: u32) {
+        self.bytes.extend_from_slice(&v.to_be_bytes());
+    }
+    fn push_bytes(&mut self, v: &[u8]) {
+        self.bytes.extend_from_slice(v);
+    }
+}
+
+/// Inverse of [`RawBuilder`].
+struct RawReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+impl<'a> RawReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+    fn read_u8(&mut self) -> Option<u8> {
+        let b = *self.data.get(self.pos)?;
+        self.pos += 1;
+        Some(b)
+    }
+    fn read_u32(&mut self) -> Option<u32> {
+        let bytes = self.read_bytes(4)?;
+        Some(u32::from_be_bytes(bytes.try_into().ok()?))
+    }
+    fn read_bytes(&mut self, n: usize) -> Option<Vec<u8>> {
+        if self.pos + n > self.data.len() {
+            return None;
+        }
+        let out = self.data[self.pos..self.pos + n].to_vec();
+        self.pos += n;
+        Some(out)
+    }
+}
+
 #[derive(Clone, PartialEq, Eq)]
-pub struct IMF {
+pub struct IMF<T: TileScalar + Copy + Eq> {
     pub width: DimensionType,
     pub height: DimensionType,
-    pub layers: Vec<Vec<Tile>>,
+    pub layers: Vec<Vec<Tile<T>>>,
 }
-impl std::fmt::Debug for IMF {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl<T: TileScalar + Copy + Eq + fmt::Debug> fmt::Debug for IMF<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         writeln!(f, "IMF {{")?;
         writeln!(f, "  width: {},", self.width)?;
         writeln!(f, "  height: {},", self.height)?;
@@ -25,19 +134,20 @@ impl std::fmt::Debug for IMF {
         writeln!(f, "}}")
     }
 }
-impl IMF {
-    pub fn new(width: DimensionType, height: DimensionType, fill: Tile) -> IMF {
+impl<T: TileScalar + Copy + Eq> IMF<T> {
+    pub fn new(width: DimensionType, height: DimensionType, fill: Tile<T>) -> IMF<T> {
         IMF {
             width,
             height,
             layers: vec![vec![fill; (width * height) as usize]],
         }
     }
+    #[allow(clippy::result_unit_err)]
     pub fn new_with_layers(
         width: DimensionType,
         height: DimensionType,
-        fill: Vec<Tile>,
-    ) -> Result<IMF, ()> {
+        fill: Vec<Tile<T>>,
+    ) -> Result<IMF<T>, ()> {
         Ok(IMF {
             width,
             height,
@@ -49,24 +159,25 @@ impl IMF {
     }
 
     pub(crate) fn ser_v3(&self) -> Vec<u8> {
-        let mut bb = ByteBuilder::new();
+        let mut bb = RawBuilder::new();
         bb.push_u8(3);
         bb.push_u32(self.width);
         bb.push_u32(self.height);
+        bb.push_u8(core::mem::size_of::<T>() as u8);
         bb.push_u32(self.layers.len() as u32);
         for map in &self.layers {
             for tile in map {
                 match tile {
                     Tile::Int(t) => {
                         bb.push_u8(0);
-                        bb.push_i16(*t)
+                        bb.push_bytes(&t.to_bytes());
                     }
                     Tile::Sides(sides) => {
                         bb.push_u8(1);
-                        bb.push_i16(sides.n);
-                        bb.push_i16(sides.e);
-                        bb.push_i16(sides.s);
-                        bb.push_i16(sides.w);
+                        bb.push_bytes(&sides.n.to_bytes());
+                        bb.push_bytes(&sides.e.to_bytes());
+                        bb.push_bytes(&sides.s.to_bytes());
+                        bb.push_bytes(&sides.w.to_bytes());
                     }
                 }
             }
@@ -74,25 +185,51 @@ impl IMF {
         bb.bytes
     }
 
-    pub(crate) fn deser_v3(br: &mut ByteReader) -> Option<Self> {
+    /// Ceiling on `(width as u64 * height as u64).max(1) * layer_count` accepted
+    /// by the decoders. For v4 (RLE), a single run legitimately expands a
+    /// handful of bytes into millions of tiles - that's the entire point of
+    /// the format - so nothing ties the claimed map size to the bytes
+    /// actually sent. v3 looks safe by the same argument (producing N tiles
+    /// costs at least N bytes on the wire) but that only holds once tiles are
+    /// actually read: when `tile_total` is zero (e.g. a zero width or
+    /// height), the outer `layer_count` loop runs unchecked and a raw `u32`
+    /// off the wire can still drive a huge `Vec<Vec<_>>` allocation before a
+    /// single tile is read. Reject headers past a sane map size instead of
+    /// letting a <30-byte payload drive a multi-gigabyte allocation.
+    const MAX_RLE_TILE_TOTAL: u64 = 16 * 1024 * 1024;
+
+    /// `true` if `tile_total.max(1) * layer_count` stays within
+    /// [`Self::MAX_RLE_TILE_TOTAL`]. `tile_total` is `.max(1)`-ed so a
+    /// `tile_total` of zero (a zero width or height) can't let an unbounded
+    /// `layer_count` slip through unchecked.
+    fn header_size_is_sane(tile_total: u64, layer_count: u32) -> bool {
+        tile_total.max(1).saturating_mul(layer_count as u64) <= Self::MAX_RLE_TILE_TOTAL
+    }
+
+    pub(crate) fn deser_v3(br: &mut RawReader) -> Option<Self> {
         let width = br.read_u32()?;
         let height = br.read_u32()?;
+        let byte_width = br.read_u8()? as usize;
         let layer_count = br.read_u32()?;
+        let tile_total = width as u64 * height as u64;
+        if tile_total == 0 && !Self::header_size_is_sane(tile_total, layer_count) {
+            return None;
+        }
         let mut layers = Vec::new();
         for _ in 0..layer_count {
             let mut layer = Vec::new();
-            for _ in 0..(width * height) {
+            for _ in 0..tile_total {
                 let tile_type = br.read_u8()?;
                 match tile_type {
                     0 => {
-                        let t = br.read_i16()?;
+                        let t = T::from_bytes(&br.read_bytes(byte_width)?)?;
                         layer.push(Tile::Int(t));
                     }
                     1 => {
-                        let n = br.read_i16()?;
-                        let e = br.read_i16()?;
-                        let s = br.read_i16()?;
-                        let w = br.read_i16()?;
+                        let n = T::from_bytes(&br.read_bytes(byte_width)?)?;
+                        let e = T::from_bytes(&br.read_bytes(byte_width)?)?;
+                        let s = T::from_bytes(&br.read_bytes(byte_width)?)?;
+                        let w = T::from_bytes(&br.read_bytes(byte_width)?)?;
                         layer.push(Tile::Sides(Sides { n, e, s, w }));
                     }
                     _ => return None,
@@ -107,7 +244,105 @@ impl IMF {
         })
     }
 
-    pub fn get(&self, x: DimensionType, y: DimensionType, layer: usize) -> Option<&Tile> {
+    pub(crate) fn ser_v4(&self) -> Vec<u8> {
+        let mut bb = RawBuilder::new();
+        bb.push_u8(4);
+        bb.push_u32(self.width);
+        bb.push_u32(self.height);
+        bb.push_u8(core::mem::size_of::<T>() as u8);
+        bb.push_u32(self.layers.len() as u32);
+        for map in &self.layers {
+            let mut runs: Vec<(u32, &Tile<T>)> = Vec::new();
+            for tile in map {
+                match runs.last_mut() {
+                    Some((count, last)) if *last == tile => *count += 1,
+                    _ => runs.push((1, tile)),
+                }
+            }
+            for (count, tile) in runs {
+                bb.push_u32(count);
+                match tile {
+                    Tile::Int(t) => {
+                        bb.push_u8(0);
+                        bb.push_bytes(&t.to_bytes());
+                    }
+                    Tile::Sides(sides) => {
+                        bb.push_u8(1);
+                        bb.push_bytes(&sides.n.to_bytes());
+                        bb.push_bytes(&sides.e.to_bytes());
+                        bb.push_bytes(&sides.s.to_bytes());
+                        bb.push_bytes(&sides.w.to_bytes());
+                    }
+                }
+            }
+        }
+        bb.bytes
+    }
+
+    pub(crate) fn deser_v4(br: &mut RawReader) -> Option<Self> {
+        let width = br.read_u32()?;
+        let height = br.read_u32()?;
+        let byte_width = br.read_u8()? as usize;
+        let layer_count = br.read_u32()?;
+        let tile_total = width as u64 * height as u64;
+        if !Self::header_size_is_sane(tile_total, layer_count) {
+            return None;
+        }
+        let mut layers = Vec::new();
+        for _ in 0..layer_count {
+            let mut layer = Vec::new();
+            let mut produced: u64 = 0;
+            while produced < tile_total {
+                let count = br.read_u32()?;
+                let tile_type = br.read_u8()?;
+                let tile = match tile_type {
+                    0 => Tile::Int(T::from_bytes(&br.read_bytes(byte_width)?)?),
+                    1 => {
+                        let n = T::from_bytes(&br.read_bytes(byte_width)?)?;
+                        let e = T::from_bytes(&br.read_bytes(byte_width)?)?;
+                        let s = T::from_bytes(&br.read_bytes(byte_width)?)?;
+                        let w = T::from_bytes(&br.read_bytes(byte_width)?)?;
+                        Tile::Sides(Sides { n, e, s, w })
+                    }
+                    _ => return None,
+                };
+                produced += count as u64;
+                if produced > tile_total {
+                    return None;
+                }
+                layer.extend(core::iter::repeat_n(tile, count as usize));
+            }
+            layers.push(layer);
+        }
+        Some(IMF {
+            width,
+            height,
+            layers,
+        })
+    }
+
+    /// Serializes using whichever of the v3 (dense) or v4 (run-length-encoded)
+    /// formats produces the smaller output.
+    pub fn to_bytes_rle(&self) -> Vec<u8> {
+        let v3 = self.ser_v3();
+        let v4 = self.ser_v4();
+        if v4.len() < v3.len() { v4 } else { v3 }
+    }
+
+    /// Inverse of [`IMF::to_bytes_rle`]: decodes whichever of v3/v4 the header
+    /// indicates. Unlike the `bytebuilder::traits::Byteable` impl below, this
+    /// is available with the `std` feature off.
+    pub fn from_bytes_rle(bytes: &[u8]) -> Option<Self> {
+        let mut br = RawReader::new(bytes);
+        let version = br.read_u8()?;
+        match version {
+            3 => IMF::deser_v3(&mut br),
+            4 => IMF::deser_v4(&mut br),
+            _ => None,
+        }
+    }
+
+    pub fn get(&self, x: DimensionType, y: DimensionType, layer: usize) -> Option<&Tile<T>> {
         if x >= self.width || y >= self.height {
             return None;
         }
@@ -120,7 +355,7 @@ impl IMF {
         x: DimensionType,
         y: DimensionType,
         layer: usize,
-        tile: Tile,
+        tile: Tile<T>,
     ) -> Option<()> {
         if x >= self.width || y >= self.height {
             return None;
@@ -134,56 +369,273 @@ impl IMF {
             None
         }
     }
-    pub fn get_layer(&self, layer: usize) -> Option<&[Tile]> {
+    pub fn get_layer(&self, layer: usize) -> Option<&[Tile<T>]> {
         self.layers.get(layer).map(|l| l.as_slice())
     }
-    pub fn get_layer_mut(&mut self, layer: usize) -> Option<&mut [Tile]> {
+    pub fn get_layer_mut(&mut self, layer: usize) -> Option<&mut [Tile<T>]> {
         self.layers.get_mut(layer).map(|l| l.as_mut_slice())
     }
+
+    /// Sets every cell in the inclusive rectangle `[x0, x1] x [y0, y1]` to `tile`.
+    pub fn fill_rect(
+        &mut self,
+        x0: DimensionType,
+        y0: DimensionType,
+        x1: DimensionType,
+        y1: DimensionType,
+        layer: usize,
+        tile: Tile<T>,
+    ) {
+        for y in y0..=y1 {
+            for x in x0..=x1 {
+                self.set(x, y, layer, tile.clone());
+            }
+        }
+    }
+
+    /// Replaces the 4-connected region of cells equal to the tile at `(x, y)` with
+    /// `new_tile`, returning the number of cells changed.
+    pub fn flood_fill(
+        &mut self,
+        x: DimensionType,
+        y: DimensionType,
+        layer: usize,
+        new_tile: Tile<T>,
+    ) -> Option<usize> {
+        let target = self.get(x, y, layer)?.clone();
+        if target == new_tile {
+            return Some(0);
+        }
+        let mut stack = vec![(x, y)];
+        let mut changed = 0;
+        while let Some((cx, cy)) = stack.pop() {
+            if self.get(cx, cy, layer) != Some(&target) {
+                continue;
+            }
+            self.set(cx, cy, layer, new_tile.clone());
+            changed += 1;
+            if cx > 0 {
+                stack.push((cx - 1, cy));
+            }
+            if cx + 1 < self.width {
+                stack.push((cx + 1, cy));
+            }
+            if cy > 0 {
+                stack.push((cx, cy - 1));
+            }
+            if cy + 1 < self.height {
+                stack.push((cx, cy + 1));
+            }
+        }
+        Some(changed)
+    }
 }
 
-impl Byteable for IMF {
+#[cfg(feature = "std")]
+impl<T: TileScalar + Copy + Eq> IMF<T> {
+    /// Streams the v3 (dense) format directly to `w`, tile by tile, without
+    /// materializing the whole map in memory first. Always v3: picking the
+    /// smaller of v3/v4 (as [`IMF::to_bytes`] does) needs the full encoded size
+    /// up front, which defeats the point of streaming. Use [`IMF::to_bytes_rle`]
+    /// if you need the smaller format and can afford to buffer it.
+    pub fn write_to<W: std::io::Write>(&self, w: &mut W) -> std::io::Result<()> {
+        w.write_all(&[3])?;
+        w.write_all(&self.width.to_be_bytes())?;
+        w.write_all(&self.height.to_be_bytes())?;
+        w.write_all(&[core::mem::size_of::<T>() as u8])?;
+        w.write_all(&(self.layers.len() as u32).to_be_bytes())?;
+        for map in &self.layers {
+            for tile in map {
+                match tile {
+                    Tile::Int(t) => {
+                        w.write_all(&[0])?;
+                        w.write_all(&t.to_bytes())?;
+                    }
+                    Tile::Sides(sides) => {
+                        w.write_all(&[1])?;
+                        w.write_all(&sides.n.to_bytes())?;
+                        w.write_all(&sides.e.to_bytes())?;
+                        w.write_all(&sides.s.to_bytes())?;
+                        w.write_all(&sides.w.to_bytes())?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads a map streamed by [`IMF::write_to`] (v3), or a v4 run-length-encoded
+    /// file such as one produced by [`IMF::to_bytes`], directly from `r`, tile by
+    /// tile, without buffering the whole payload first.
+    pub fn read_from<R: std::io::Read>(r: &mut R) -> std::io::Result<Self> {
+        use std::io::{Error, ErrorKind};
+        let mut version = [0u8; 1];
+        r.read_exact(&mut version)?;
+        match version[0] {
+            3 => Self::read_from_v3(r),
+            4 => Self::read_from_v4(r),
+            v => Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("unsupported IMF version {v}"),
+            )),
+        }
+    }
+
+    /// Reads the version-independent header: width, height, tile byte width,
+    /// and layer count.
+    fn read_header<R: std::io::Read>(r: &mut R) -> std::io::Result<(u32, u32, usize, u32)> {
+        let mut buf4 = [0u8; 4];
+        r.read_exact(&mut buf4)?;
+        let width = u32::from_be_bytes(buf4);
+        r.read_exact(&mut buf4)?;
+        let height = u32::from_be_bytes(buf4);
+        let mut buf1 = [0u8; 1];
+        r.read_exact(&mut buf1)?;
+        let byte_width = buf1[0] as usize;
+        r.read_exact(&mut buf4)?;
+        let layer_count = u32::from_be_bytes(buf4);
+        Ok((width, height, byte_width, layer_count))
+    }
+
+    fn read_from_v3<R: std::io::Read>(r: &mut R) -> std::io::Result<Self> {
+        use std::io::{Error, ErrorKind};
+        let invalid = || Error::new(ErrorKind::InvalidData, "malformed IMF tile");
+        let (width, height, byte_width, layer_count) = Self::read_header(r)?;
+        let tile_total = width as u64 * height as u64;
+        // A zero `tile_total` (zero width or height) means the per-tile loop
+        // below never consumes a byte, so a raw `layer_count` off the wire
+        // would otherwise drive the outer `Vec::push` loop unchecked.
+        if tile_total == 0 && !Self::header_size_is_sane(tile_total, layer_count) {
+            return Err(invalid());
+        }
+
+        let mut tile_buf = vec![0u8; byte_width];
+        let mut buf1 = [0u8; 1];
+        let mut read_scalar = |r: &mut R| -> std::io::Result<T> {
+            r.read_exact(&mut tile_buf)?;
+            T::from_bytes(&tile_buf).ok_or_else(invalid)
+        };
+
+        // Grown incrementally, not pre-sized from `layer_count`/`tile_total`: those
+        // come straight off the wire and a corrupt header must not trigger a huge
+        // up-front allocation before a single tile has been validated.
+        let mut layers = Vec::new();
+        for _ in 0..layer_count {
+            let mut layer = Vec::new();
+            for _ in 0..tile_total {
+                r.read_exact(&mut buf1)?;
+                let tile = match buf1[0] {
+                    0 => Tile::Int(read_scalar(r)?),
+                    1 => {
+                        let n = read_scalar(r)?;
+                        let e = read_scalar(r)?;
+                        let s = read_scalar(r)?;
+                        let w = read_scalar(r)?;
+                        Tile::Sides(Sides { n, e, s, w })
+                    }
+                    _ => return Err(invalid()),
+                };
+                layer.push(tile);
+            }
+            layers.push(layer);
+        }
+        Ok(IMF {
+            width,
+            height,
+            layers,
+        })
+    }
+
+    fn read_from_v4<R: std::io::Read>(r: &mut R) -> std::io::Result<Self> {
+        use std::io::{Error, ErrorKind};
+        let invalid = || Error::new(ErrorKind::InvalidData, "malformed IMF tile");
+        let (width, height, byte_width, layer_count) = Self::read_header(r)?;
+        let tile_total = width as u64 * height as u64;
+        if !Self::header_size_is_sane(tile_total, layer_count) {
+            return Err(invalid());
+        }
+
+        let mut tile_buf = vec![0u8; byte_width];
+        let mut buf1 = [0u8; 1];
+        let mut buf4 = [0u8; 4];
+        let mut read_scalar = |r: &mut R| -> std::io::Result<T> {
+            r.read_exact(&mut tile_buf)?;
+            T::from_bytes(&tile_buf).ok_or_else(invalid)
+        };
+
+        let mut layers = Vec::new();
+        for _ in 0..layer_count {
+            let mut layer = Vec::new();
+            let mut produced: u64 = 0;
+            while produced < tile_total {
+                r.read_exact(&mut buf4)?;
+                let count = u32::from_be_bytes(buf4);
+                r.read_exact(&mut buf1)?;
+                let tile = match buf1[0] {
+                    0 => Tile::Int(read_scalar(r)?),
+                    1 => {
+                        let n = read_scalar(r)?;
+                        let e = read_scalar(r)?;
+                        let s = read_scalar(r)?;
+                        let w = read_scalar(r)?;
+                        Tile::Sides(Sides { n, e, s, w })
+                    }
+                    _ => return Err(invalid()),
+                };
+                produced += count as u64;
+                if produced > tile_total {
+                    return Err(invalid());
+                }
+                layer.extend(core::iter::repeat_n(tile, count as usize));
+            }
+            layers.push(layer);
+        }
+        Ok(IMF {
+            width,
+            height,
+            layers,
+        })
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: TileScalar + Copy + Eq> Byteable for IMF<T> {
     fn to_bytes(&self) -> Vec<u8> {
-        self.ser_v3()
+        self.to_bytes_rle()
     }
 
     fn from_bytes(bytes: &[u8]) -> Option<Self> {
-        let mut br = ByteReader::new(bytes);
-        let version = br.read_u8()?;
-        match version {
-            3 => IMF::deser_v3(&mut br),
-            _ => None,
-        }
+        IMF::from_bytes_rle(bytes)
     }
 }
 
-impl Default for IMF {
+impl Default for IMF<i16> {
     fn default() -> Self {
         IMF::new(8, 8, Tile::Int(0))
     }
 }
 #[derive(Clone, PartialEq, Eq)]
-pub enum Tile {
-    Int(TileType),
-    Sides(Sides),
+pub enum Tile<T: TileScalar + Copy + Eq> {
+    Int(T),
+    Sides(Sides<T>),
 }
-impl std::fmt::Debug for Tile {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl<T: TileScalar + Copy + Eq + fmt::Debug> fmt::Debug for Tile<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Tile::Int(t) => write!(f, "i{}", t),
+            Tile::Int(t) => write!(f, "i{:?}", t),
             Tile::Sides(sides) => write!(f, "s[{:?}]", sides),
         }
     }
 }
 
-impl Tile {
+impl<T: TileScalar + Copy + Eq> Tile<T> {
     pub fn is_int(&self) -> bool {
         matches!(self, Tile::Int(_))
     }
     pub fn is_sides(&self) -> bool {
         matches!(self, Tile::Sides(_))
     }
-    pub fn force_int(&self) -> TileType {
+    pub fn force_int(&self) -> T {
         match self {
             Tile::Int(t) => *t,
             Tile::Sides(Sides {
@@ -194,7 +646,7 @@ impl Tile {
             }) => *n,
         }
     }
-    pub fn force_sides(&self) -> Sides {
+    pub fn force_sides(&self) -> Sides<T> {
         match self {
             Tile::Int(t) => Sides {
                 n: *t,
@@ -208,11 +660,152 @@ impl Tile {
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
-pub struct Sides {
-    pub n: TileType,
-    pub e: TileType,
-    pub s: TileType,
-    pub w: TileType,
+pub struct Sides<T: TileScalar + Copy + Eq> {
+    pub n: T,
+    pub e: T,
+    pub s: T,
+    pub w: T,
+}
+
+fn floor_log2(n: usize) -> usize {
+    n.ilog2() as usize
+}
+
+/// A 2D sparse table over one layer of an [`IMF`], answering rectangular min/max
+/// queries in O(1). Tiles are reduced to `T` via [`Tile::force_int`].
+///
+/// This is a snapshot: it borrows no state from the source map, so calls to
+/// [`IMF::set`] after building do not update it. Rebuild after any edit to the
+/// layer it was built from.
+pub struct ImfRmq<T: TileScalar + Copy + Eq + Ord> {
+    rows: usize,
+    cols: usize,
+    min: Vec<Vec<Vec<Vec<T>>>>,
+    max: Vec<Vec<Vec<Vec<T>>>>,
+}
+
+impl<T: TileScalar + Copy + Eq + Ord> ImfRmq<T> {
+    pub fn build(imf: &IMF<T>, layer: usize) -> Option<Self> {
+        let rows = imf.height as usize;
+        let cols = imf.width as usize;
+        let layer = imf.get_layer(layer)?;
+        if rows == 0 || cols == 0 {
+            return None;
+        }
+        let kr_max = floor_log2(rows) + 1;
+        let kc_max = floor_log2(cols) + 1;
+
+        let mut base_min = vec![vec![layer[0].force_int(); cols]; rows];
+        let mut base_max = base_min.clone();
+        for i in 0..rows {
+            for j in 0..cols {
+                let v = layer[i * cols + j].force_int();
+                base_min[i][j] = v;
+                base_max[i][j] = v;
+            }
+        }
+
+        let mut min_row0 = vec![base_min];
+        let mut max_row0 = vec![base_max];
+        for kc in 1..kc_max {
+            let half = 1usize << (kc - 1);
+            let prev_min = &min_row0[kc - 1];
+            let prev_max = &max_row0[kc - 1];
+            let mut cur_min = prev_min.clone();
+            let mut cur_max = prev_max.clone();
+            for i in 0..rows {
+                for j in 0..(cols.saturating_sub((1 << kc) - 1)) {
+                    cur_min[i][j] = core::cmp::min(prev_min[i][j], prev_min[i][j + half]);
+                    cur_max[i][j] = core::cmp::max(prev_max[i][j], prev_max[i][j + half]);
+                }
+            }
+            min_row0.push(cur_min);
+            max_row0.push(cur_max);
+        }
+
+        let mut min = vec![min_row0];
+        let mut max = vec![max_row0];
+        for kr in 1..kr_max {
+            let half = 1usize << (kr - 1);
+            let mut min_kr = Vec::with_capacity(kc_max);
+            let mut max_kr = Vec::with_capacity(kc_max);
+            for kc in 0..kc_max {
+                let prev_min = &min[kr - 1][kc];
+                let prev_max = &max[kr - 1][kc];
+                let mut cur_min = prev_min.clone();
+                let mut cur_max = prev_max.clone();
+                for i in 0..(rows.saturating_sub((1 << kr) - 1)) {
+                    for j in 0..cols {
+                        cur_min[i][j] = core::cmp::min(prev_min[i][j], prev_min[i + half][j]);
+                        cur_max[i][j] = core::cmp::max(prev_max[i][j], prev_max[i + half][j]);
+                    }
+                }
+                min_kr.push(cur_min);
+                max_kr.push(cur_max);
+            }
+            min.push(min_kr);
+            max.push(max_kr);
+        }
+
+        Some(Self {
+            rows,
+            cols,
+            min,
+            max,
+        })
+    }
+
+    fn query(
+        table: &[Vec<Vec<Vec<T>>>],
+        (rows, cols): (usize, usize),
+        (r1, c1, r2, c2): (DimensionType, DimensionType, DimensionType, DimensionType),
+        combine: fn(T, T) -> T,
+    ) -> Option<T> {
+        let (r1, c1, r2, c2) = (r1 as usize, c1 as usize, r2 as usize, c2 as usize);
+        if r1 > r2 || c1 > c2 || r2 >= rows || c2 >= cols {
+            return None;
+        }
+        let kr = floor_log2(r2 - r1 + 1);
+        let kc = floor_log2(c2 - c1 + 1);
+        let level = &table[kr][kc];
+        let a = level[r1][c1];
+        let b = level[r1][c2 + 1 - (1 << kc)];
+        let c = level[r2 + 1 - (1 << kr)][c1];
+        let d = level[r2 + 1 - (1 << kr)][c2 + 1 - (1 << kc)];
+        Some(combine(combine(a, b), combine(c, d)))
+    }
+
+    /// Returns the minimum tile value in the inclusive rectangle `[r1, r2] x [c1, c2]`.
+    pub fn query_min(
+        &self,
+        r1: DimensionType,
+        c1: DimensionType,
+        r2: DimensionType,
+        c2: DimensionType,
+    ) -> Option<T> {
+        Self::query(
+            &self.min,
+            (self.rows, self.cols),
+            (r1, c1, r2, c2),
+            core::cmp::min,
+        )
+    }
+
+    /// Returns the maximum tile value in the inclusive rectangle `[r1, r2] x [c1, c2]`.
+    pub fn query_max(
+        &self,
+        r1: DimensionType,
+        c1: DimensionType,
+        r2: DimensionType,
+        c2: DimensionType,
+    ) -> Option<T> {
+        Self::query(
+            &self.max,
+            (self.rows, self.cols),
+            (r1, c1, r2, c2),
+            core::cmp::max,
+        )
+    }
 }
 
 #[cfg(test)]
@@ -221,14 +814,216 @@ mod tests {
 
     #[test]
     fn test_imf() {
-        let mut imf =
+        let mut imf: ImfI16 =
             IMF::new_with_layers(3, 3, vec![Tile::Int(0), Tile::Int(1), Tile::Int(2)]).unwrap();
         imf.set(1, 0, 0, Tile::Int(1)).unwrap();
-        println!("{:?}", imf);
-        let bytes = imf.to_bytes();
-        let imf2 = IMF::from_bytes(&bytes).unwrap();
+        let bytes = imf.to_bytes_rle();
+        let imf2 = ImfI16::from_bytes_rle(&bytes).unwrap();
+        assert_eq!(imf.width, imf2.width);
+        assert_eq!(imf.height, imf2.height);
+        assert_eq!(imf.layers, imf2.layers);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn byteable_impl_round_trips_through_bytebuilder() {
+        let imf: ImfI16 = IMF::new(2, 2, Tile::Int(3));
+        let bytes = <ImfI16 as Byteable>::to_bytes(&imf);
+        let imf2 = <ImfI16 as Byteable>::from_bytes(&bytes).unwrap();
+        assert_eq!(imf.layers, imf2.layers);
+    }
+
+    #[test]
+    fn fill_rect_clips_to_map_bounds() {
+        let mut imf: ImfI16 = IMF::new(3, 3, Tile::Int(0));
+        imf.fill_rect(1, 1, 5, 5, 0, Tile::Int(9));
+        assert_eq!(imf.get(1, 1, 0), Some(&Tile::Int(9)));
+        assert_eq!(imf.get(2, 2, 0), Some(&Tile::Int(9)));
+        assert_eq!(imf.get(0, 0, 0), Some(&Tile::Int(0)));
+    }
+
+    #[test]
+    fn flood_fill_counts_region_and_noops_on_same_tile() {
+        let mut imf: ImfI16 = IMF::new(3, 3, Tile::Int(0));
+        imf.set(2, 2, 0, Tile::Int(1)).unwrap();
+        assert_eq!(imf.flood_fill(0, 0, 0, Tile::Int(0)), Some(0));
+        assert_eq!(imf.flood_fill(0, 0, 0, Tile::Int(7)), Some(8));
+        assert_eq!(imf.get(1, 1, 0), Some(&Tile::Int(7)));
+        assert_eq!(imf.get(2, 2, 0), Some(&Tile::Int(1)));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn write_to_read_from_round_trip() {
+        let mut imf: ImfI16 =
+            IMF::new_with_layers(3, 3, vec![Tile::Int(0), Tile::Int(1)]).unwrap();
+        imf.set(1, 0, 0, Tile::Int(2)).unwrap();
+
+        let mut buf = Vec::new();
+        imf.write_to(&mut buf).unwrap();
+        let mut cursor = buf.as_slice();
+        let imf2 = ImfI16::read_from(&mut cursor).unwrap();
+
         assert_eq!(imf.width, imf2.width);
         assert_eq!(imf.height, imf2.height);
         assert_eq!(imf.layers, imf2.layers);
     }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn read_from_accepts_v4_payload() {
+        let mut imf: ImfI16 = IMF::new(4, 4, Tile::Int(0));
+        imf.set(0, 0, 0, Tile::Int(9)).unwrap();
+
+        let bytes = imf.ser_v4();
+        let mut cursor = bytes.as_slice();
+        let imf2 = ImfI16::read_from(&mut cursor).unwrap();
+
+        assert_eq!(imf.layers, imf2.layers);
+    }
+
+    #[test]
+    fn deser_v3_rejects_overflowing_header_without_panicking() {
+        let mut bb = RawBuilder::new();
+        bb.push_u8(3);
+        bb.push_u32(u32::MAX);
+        bb.push_u32(2);
+        bb.push_u8(2);
+        bb.push_u32(1);
+        assert_eq!(ImfI16::from_bytes_rle(&bb.bytes), None);
+    }
+
+    #[test]
+    fn deser_v4_rejects_overflowing_header_without_panicking() {
+        let mut bb = RawBuilder::new();
+        bb.push_u8(4);
+        bb.push_u32(u32::MAX);
+        bb.push_u32(2);
+        bb.push_u8(2);
+        bb.push_u32(1);
+        assert_eq!(ImfI16::from_bytes_rle(&bb.bytes), None);
+    }
+
+    #[test]
+    fn deser_v4_rejects_tile_total_past_the_rle_ceiling() {
+        // A tiny, well-formed-looking payload claiming a 50M-tile map: one run
+        // whose count matches width*height exactly, so without a ceiling on
+        // tile_total this decodes "successfully" into a huge allocation.
+        let mut bb = RawBuilder::new();
+        bb.push_u8(4);
+        bb.push_u32(50_000_000);
+        bb.push_u32(1);
+        bb.push_u8(2);
+        bb.push_u32(1);
+        bb.push_u32(50_000_000);
+        bb.push_u8(0);
+        bb.push_bytes(&0i16.to_bytes());
+        assert_eq!(ImfI16::from_bytes_rle(&bb.bytes), None);
+    }
+
+    #[test]
+    fn deser_rejects_huge_layer_count_with_zero_tile_total() {
+        // width/height of 0 means tile_total is 0, so the per-layer loop never
+        // reads a tile - without a layer_count ceiling, a 14-byte payload would
+        // decode "successfully" into a 100M-entry `Vec<Vec<_>>`.
+        let mut v3 = RawBuilder::new();
+        v3.push_u8(3);
+        v3.push_u32(0);
+        v3.push_u32(0);
+        v3.push_u8(2);
+        v3.push_u32(100_000_000);
+        assert_eq!(ImfI16::from_bytes_rle(&v3.bytes), None);
+
+        let mut v4 = RawBuilder::new();
+        v4.push_u8(4);
+        v4.push_u32(0);
+        v4.push_u32(0);
+        v4.push_u8(2);
+        v4.push_u32(100_000_000);
+        assert_eq!(ImfI16::from_bytes_rle(&v4.bytes), None);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn read_from_v4_rejects_tile_total_past_the_rle_ceiling() {
+        let mut bb = RawBuilder::new();
+        bb.push_u8(4);
+        bb.push_u32(50_000_000);
+        bb.push_u32(1);
+        bb.push_u8(2);
+        bb.push_u32(1);
+        bb.push_u32(50_000_000);
+        bb.push_u8(0);
+        bb.push_bytes(&0i16.to_bytes());
+        let mut cursor = bb.bytes.as_slice();
+        assert!(ImfI16::read_from(&mut cursor).is_err());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn read_from_rejects_huge_layer_count_with_zero_tile_total() {
+        let mut v3 = RawBuilder::new();
+        v3.push_u8(3);
+        v3.push_u32(0);
+        v3.push_u32(0);
+        v3.push_u8(2);
+        v3.push_u32(100_000_000);
+        let mut cursor = v3.bytes.as_slice();
+        assert!(ImfI16::read_from(&mut cursor).is_err());
+
+        let mut v4 = RawBuilder::new();
+        v4.push_u8(4);
+        v4.push_u32(0);
+        v4.push_u32(0);
+        v4.push_u8(2);
+        v4.push_u32(100_000_000);
+        let mut cursor = v4.bytes.as_slice();
+        assert!(ImfI16::read_from(&mut cursor).is_err());
+    }
+
+    #[test]
+    fn rmq_single_cell() {
+        let imf: ImfI16 = IMF::new(1, 1, Tile::Int(5));
+        let rmq = ImfRmq::build(&imf, 0).unwrap();
+        assert_eq!(rmq.query_min(0, 0, 0, 0), Some(5));
+        assert_eq!(rmq.query_max(0, 0, 0, 0), Some(5));
+    }
+
+    #[test]
+    fn rmq_matches_brute_force() {
+        let values: [i16; 20] = [
+            3, 1, 4, 1, 5, //
+            9, 2, 6, 5, 3, //
+            5, 8, 9, 7, 9, //
+            3, 2, 3, 8, 4, //
+        ];
+        let mut imf: ImfI16 = IMF::new(5, 4, Tile::Int(0));
+        for y in 0..4u32 {
+            for x in 0..5u32 {
+                imf.set(x, y, 0, Tile::Int(values[(y * 5 + x) as usize]))
+                    .unwrap();
+            }
+        }
+        let rmq = ImfRmq::build(&imf, 0).unwrap();
+
+        for r1 in 0..4u32 {
+            for r2 in r1..4u32 {
+                for c1 in 0..5u32 {
+                    for c2 in c1..5u32 {
+                        let mut expected_min = i16::MAX;
+                        let mut expected_max = i16::MIN;
+                        for y in r1..=r2 {
+                            for x in c1..=c2 {
+                                let v = values[(y * 5 + x) as usize];
+                                expected_min = expected_min.min(v);
+                                expected_max = expected_max.max(v);
+                            }
+                        }
+                        assert_eq!(rmq.query_min(r1, c1, r2, c2), Some(expected_min));
+                        assert_eq!(rmq.query_max(r1, c1, r2, c2), Some(expected_max));
+                    }
+                }
+            }
+        }
+    }
 }